@@ -1,5 +1,5 @@
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{quote, format_ident};
 use syn::punctuated::Punctuated;
 use syn::{
     parse_macro_input, Data, DeriveInput, Error, Expr, Fields, Lit, Meta, GenericParam, Generics, parse_quote,
@@ -20,20 +20,19 @@ pub fn derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 }
 
 fn expand(input: DeriveInput) -> Result<TokenStream> {
-    let input_cloned = input.clone();
+    let mut input_cloned = input.clone();
+    input_cloned.generics = without_defaults(&input_cloned.generics);
     let generics = add_trait_bounds(input_cloned)?;
     let name = input.ident;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let debug_fields = debug_fields(&input.data);
+    let body = debug_body(&name, &input.data);
 
     let expanded = quote! {
         // The generated impl.
         impl #impl_generics std::fmt::Debug for #name #ty_generics #where_clause {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-                f.debug_struct(stringify!(#name))    
-                #debug_fields
-                .finish()
+                #body
             }
         }
     };
@@ -43,15 +42,34 @@ fn expand(input: DeriveInput) -> Result<TokenStream> {
 
 struct TypePathVisitor {
     generic_type_names: Vec<String>, // record all generic types `T`,`U`
+    direct_uses: Vec<String>, // record generics used directly, e.g. `T` in `Vec<T>`
     associated_types: HashMap<String, Vec<syn::TypePath>>, // record all associated types `T::Value` under generic type `T`
 }
 
 impl<'ast> Visit<'ast> for TypePathVisitor {
+    fn visit_type(&mut self, node: &'ast syn::Type) {
+        // A param buried inside `PhantomData<..>` does not need a `Debug` bound, so don't
+        // descend into it (mirrors the old outermost-`PhantomData` special case).
+        if let syn::Type::Path(type_path) = node {
+            if let Some(segment) = type_path.path.segments.last() {
+                if segment.ident == "PhantomData" {
+                    return;
+                }
+            }
+        }
+        visit::visit_type(self, node);
+    }
+
     fn visit_type_path(&mut self, node: &'ast syn::TypePath) {
-        
-        if node.path.segments.len() >= 2 {
-            let generic_type_name = node.path.segments[0].ident.to_string();
-            if self.generic_type_names.contains(&generic_type_name) {
+        let generic_type_name = node.path.segments[0].ident.to_string();
+        if self.generic_type_names.contains(&generic_type_name) {
+            if node.path.segments.len() == 1 && node.qself.is_none() {
+                // Direct use of the parameter, e.g. `Vec<T>` -> `T: Debug`.
+                if !self.direct_uses.contains(&generic_type_name) {
+                    self.direct_uses.push(generic_type_name);
+                }
+            } else {
+                // Use only through an associated path, e.g. `T::Assoc` -> `T::Assoc: Debug`.
                 self.associated_types.entry(generic_type_name).or_default().push(node.clone());
             }
         }
@@ -60,35 +78,40 @@ impl<'ast> Visit<'ast> for TypePathVisitor {
     }
 }
 
-fn get_generic_associated_types(input: &syn::DeriveInput) -> HashMap<String, Vec<syn::TypePath>> {
-    let origin_generic_param_names = input.generics.params.iter().filter_map(|f| {
-        if let syn::GenericParam::Type(ty) = f {
-            return Some(ty.ident.to_string())
-        }
-        None
-    }).collect();
-
-    let mut visitor = TypePathVisitor {
-        generic_type_names: origin_generic_param_names,
-        associated_types: HashMap::new(),
-    };
-
-    visitor.visit_derive_input(input);
-    visitor.associated_types
+fn get_fields_from_derive_input(d: &syn::DeriveInput) -> syn::Result<Vec<&syn::Field>> {
+    match &d.data {
+        syn::Data::Struct(data) => Ok(data.fields.iter().collect()),
+        syn::Data::Enum(data) => Ok(data.variants.iter().flat_map(|v| v.fields.iter()).collect()),
+        syn::Data::Union(_) => Err(syn::Error::new_spanned(
+            d,
+            "Must define on a Struct or Enum, not Union".to_string(),
+        )),
+    }
 }
 
-type StructFields = syn::punctuated::Punctuated<syn::Field,syn::Token!(,)>;
-fn get_fields_from_derive_input(d: &syn::DeriveInput) -> syn::Result<&StructFields> {
-    if let syn::Data::Struct(syn::DataStruct {
-        fields: syn::Fields::Named(syn::FieldsNamed { ref named, .. }),
-        ..
-    }) = d.data{
-        return Ok(named)
+
+// Drop any `= Default` on type parameters; defaults are illegal on an `impl`'s generics,
+// so strip them before the bounds are computed and the generics are split.
+fn without_defaults(generics: &Generics) -> Generics {
+    let params = generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(type_param) => {
+                let mut type_param = type_param.clone();
+                type_param.eq_token = None;
+                type_param.default = None;
+                GenericParam::Type(type_param)
+            }
+            other => other.clone(),
+        })
+        .collect();
+    Generics {
+        params,
+        ..generics.clone()
     }
-    Err(syn::Error::new_spanned(d, "Must define on a Struct, not Enum".to_string()))
 }
 
-
 // Add a bound `T: Debug` to every type parameter T except PhantomData<T>.
 fn add_trait_bounds(mut input: syn::DeriveInput) -> syn::Result<Generics> {
     if let Some(hatch) = get_struct_escape_hatch(&input) {
@@ -104,28 +127,35 @@ fn add_trait_bounds(mut input: syn::DeriveInput) -> syn::Result<Generics> {
     } else {
         let fields = get_fields_from_derive_input(&input)?;
 
-        let mut field_type_names = Vec::new();
-        let mut phantomdata_type_param_names = Vec::new();
-        for field in fields{
-            if let Some(s) = get_field_type_name(field)? {
-                field_type_names.push(s);
+        let generic_type_names = input.generics.params.iter().filter_map(|param| {
+            if let GenericParam::Type(ty) = param {
+                return Some(ty.ident.to_string());
             }
-            if let Some(s) = get_phantomdata_generic_type_name(field)? {
-                phantomdata_type_param_names.push(s);
+            None
+        }).collect();
+        let mut visitor = TypePathVisitor {
+            generic_type_names,
+            direct_uses: Vec::new(),
+            associated_types: HashMap::new(),
+        };
+
+        let mut field_bounds = Vec::new();
+        for field in fields{
+            field_bounds.extend(get_field_bounds(field));
+            if is_skipped(field) {
+                continue;
             }
+            visitor.visit_type(&field.ty);
         }
 
-        let associated_types_map = get_generic_associated_types(&input);
+        let direct_uses = visitor.direct_uses;
+        let associated_types_map = visitor.associated_types;
         for param in &mut input.generics.params {
             if let GenericParam::Type(ref mut type_param) = *param {
-                let type_param_name = type_param.ident.to_string(); 
-                if phantomdata_type_param_names.contains(&type_param_name) && !field_type_names.contains(&type_param_name) {
-                    continue;
-                }
-                if associated_types_map.contains_key(&type_param_name) && !field_type_names.contains(&type_param_name){
-                    continue
+                let type_param_name = type_param.ident.to_string();
+                if direct_uses.contains(&type_param_name) {
+                    type_param.bounds.push(parse_quote!(std::fmt::Debug));
                 }
-                type_param.bounds.push(parse_quote!(std::fmt::Debug));
             }
         }
 
@@ -139,6 +169,11 @@ fn add_trait_bounds(mut input: syn::DeriveInput) -> syn::Result<Generics> {
                 input.generics.where_clause.as_mut().unwrap().predicates.push(parse_quote!(#associated_type: std::fmt::Debug));
             }
         }
+
+        for bound in field_bounds {
+            let predicate: syn::WherePredicate = syn::parse_str(&bound)?;
+            input.generics.where_clause.as_mut().unwrap().predicates.push(predicate);
+        }
     }
 
     Ok(input.generics)
@@ -171,70 +206,208 @@ fn get_struct_escape_hatch(input: &syn::DeriveInput) -> Option<Vec<String>> {
     Some(escape_hatch)
 }
 
-fn get_field_type_name(field: &syn::Field) -> syn::Result<Option<String>> {
-    if let syn::Type::Path(syn::TypePath{path: syn::Path{ref segments, ..}, ..}) = field.ty {
-        if let Some(syn::PathSegment{ref ident,..}) = segments.last() {
-            return Ok(Some(ident.to_string()))
+// Gather any field-level `#[debug(bound = "...")]` predicates; these are additive and
+// do not suppress the automatic bound inference for the rest of the struct.
+fn get_field_bounds(field: &syn::Field) -> Vec<String> {
+    let mut bounds = Vec::new();
+    for attr in &field.attrs {
+        if attr.path().is_ident("debug") {
+            if let Ok(nested) = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) {
+                for meta in &nested {
+                    if let Meta::NameValue(meta) = meta {
+                        if meta.path.is_ident("bound") {
+                            if let Expr::Lit(ref expr) = meta.value {
+                                if let Lit::Str(ref lit) = expr.lit {
+                                    bounds.push(lit.value());
+                                }
+                            }
+                        }
+                    }
+                }
+            }
         }
     }
-    Ok(None)
+    bounds
 }
 
-fn get_phantomdata_generic_type_name(field: &syn::Field) -> syn::Result<Option<String>> {
-    if let syn::Type::Path(syn::TypePath{path: syn::Path{ref segments, ..}, ..}) = field.ty {
-        if let Some(syn::PathSegment{ref ident, ref arguments}) = segments.last() {
-            if ident == "PhantomData" {
-                if let syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments{args, ..}) = arguments {
-                    if let Some(syn::GenericArgument::Type(syn::Type::Path( ref gp))) = args.first() {
-                        if let Some(generic_ident) = gp.path.segments.first() {
-                            return Ok(Some(generic_ident.ident.to_string()))
-                        }
+// Whether the field carries an inert `#[debug(skip)]`, excluding it from output.
+fn is_skipped(field: &syn::Field) -> bool {
+    for attr in &field.attrs {
+        if attr.path().is_ident("debug") {
+            if let Ok(nested) = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) {
+                if nested.iter().any(|meta| matches!(meta, Meta::Path(path) if path.is_ident("skip"))) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+// Pull the per-field `#[debug = "..."]` format string, if present.
+fn get_field_debug_format(field: &syn::Field) -> Option<String> {
+    for attr in &field.attrs {
+        if attr.path().is_ident("debug") {
+            if let Meta::NameValue(ref meta) = attr.meta {
+                if let Expr::Lit(ref expr) = meta.value {
+                    if let Lit::Str(ref lit) = expr.lit {
+                        return Some(lit.value());
                     }
                 }
             }
         }
     }
-    Ok(None)
+    None
 }
 
-fn debug_fields(data: &Data) -> TokenStream {
-    match *data {
-        Data::Struct(ref data) => match data.fields {
-            Fields::Named(ref fields) => {
-                let recurse = fields.named.iter().map(|f| {
-                    let name = &f.ident;
-                    let mut debug_format = None;
-                    for attr in &f.attrs {
-                        if attr.path().is_ident("debug") {
-                            let meta = attr.meta.clone();
-                            match meta {
-                                Meta::NameValue(meta) => {
-                                    if let Expr::Lit(expr) = meta.value {
-                                        if let Lit::Str(lit) = expr.lit {
-                                            debug_format = Some(lit.value());
-                                        }
+// Pull the per-field `#[debug(format_with = "path")]` formatter path, if present.
+fn get_field_format_with(field: &syn::Field) -> Option<syn::Path> {
+    for attr in &field.attrs {
+        if attr.path().is_ident("debug") {
+            if let Ok(nested) = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) {
+                for meta in nested {
+                    if let Meta::NameValue(meta) = meta {
+                        if meta.path.is_ident("format_with") {
+                            if let Expr::Lit(expr) = meta.value {
+                                if let Lit::Str(lit) = expr.lit {
+                                    if let Ok(path) = lit.parse::<syn::Path>() {
+                                        return Some(path);
                                     }
                                 }
-                                _ => unimplemented!(),
                             }
                         }
                     }
-                    if let Some(debug_format) = debug_format {
-                        quote! {
-                            .field(stringify!(#name), &format_args!(#debug_format, &self.#name))
-                        }
-                    } else {
-                        quote! {
-                            .field(stringify!(#name), &self.#name)
-                        }
+                }
+            }
+        }
+    }
+    None
+}
+
+// Emit a single `.field(...)` call. `label` is `Some` for named fields (struct or
+// struct-variant) and `None` for tuple positions; `accessor` is the already-borrowed
+// expression that yields the field value (`&self.x`, a `ref` binding, ...).
+fn field_debug_call(label: Option<&syn::Ident>, accessor: TokenStream, field: &syn::Field) -> TokenStream {
+    let value = if let Some(path) = get_field_format_with(field) {
+        quote! {
+            &{
+                struct FmtWith<F>(F);
+                impl<F: Fn(&mut std::fmt::Formatter<'_>) -> std::fmt::Result> std::fmt::Debug for FmtWith<F> {
+                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                        (self.0)(f)
                     }
-                });
+                }
+                FmtWith(move |f: &mut std::fmt::Formatter<'_>| #path(#accessor, f))
+            }
+        }
+    } else if let Some(format) = get_field_debug_format(field) {
+        quote!(&format_args!(#format, #accessor))
+    } else {
+        accessor
+    };
+    match label {
+        Some(name) => quote!(.field(stringify!(#name), #value)),
+        None => quote!(.field(#value)),
+    }
+}
+
+// Build the `fmt` body for the whole type: a builder expression for structs, or a
+// `match self { ... }` dispatching one builder per variant for enums.
+fn debug_body(name: &syn::Ident, data: &Data) -> TokenStream {
+    match *data {
+        Data::Struct(ref data) => struct_builder(name, &data.fields),
+        Data::Enum(ref data) => {
+            let arms = data.variants.iter().map(|variant| {
+                let variant_name = &variant.ident;
+                let (pattern, builder) =
+                    variant_arm(name, variant_name, &variant.fields);
                 quote! {
-                    #(#recurse)*
+                    #pattern => #builder,
                 }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => unimplemented!(),
+    }
+}
+
+// Builder expression for a set of fields accessed through `self`.
+fn struct_builder(title: &syn::Ident, fields: &Fields) -> TokenStream {
+    match fields {
+        Fields::Named(fields) => {
+            let calls = fields.named.iter().filter(|field| !is_skipped(field)).map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                field_debug_call(Some(ident), quote!(&self.#ident), field)
+            });
+            quote! {
+                f.debug_struct(stringify!(#title)) #(#calls)* .finish()
+            }
+        }
+        Fields::Unnamed(fields) => {
+            let calls = fields.unnamed.iter().enumerate().filter(|(_, field)| !is_skipped(field)).map(|(i, field)| {
+                let index = syn::Index::from(i);
+                field_debug_call(None, quote!(&self.#index), field)
+            });
+            quote! {
+                f.debug_tuple(stringify!(#title)) #(#calls)* .finish()
             }
-            _ => unimplemented!(),
+        }
+        Fields::Unit => quote! {
+            f.debug_struct(stringify!(#title)).finish()
         },
-        _ => unimplemented!(),
+    }
+}
+
+// A single enum-variant match arm: the binding pattern plus its builder expression.
+fn variant_arm(
+    type_name: &syn::Ident,
+    variant_name: &syn::Ident,
+    fields: &Fields,
+) -> (TokenStream, TokenStream) {
+    match fields {
+        Fields::Named(fields) => {
+            let bound: Vec<_> = fields
+                .named
+                .iter()
+                .filter(|field| !is_skipped(field))
+                .map(|field| field.ident.clone().unwrap())
+                .collect();
+            let calls = fields.named.iter().filter(|field| !is_skipped(field)).map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                field_debug_call(Some(ident), quote!(#ident), field)
+            });
+            let pattern = quote!(#type_name::#variant_name { #(ref #bound,)* .. });
+            let builder = quote! {
+                f.debug_struct(stringify!(#variant_name)) #(#calls)* .finish()
+            };
+            (pattern, builder)
+        }
+        Fields::Unnamed(fields) => {
+            let mut patterns = Vec::new();
+            let mut calls = Vec::new();
+            for (i, field) in fields.unnamed.iter().enumerate() {
+                if is_skipped(field) {
+                    patterns.push(quote!(_));
+                } else {
+                    let binding = format_ident!("__{}", i);
+                    patterns.push(quote!(ref #binding));
+                    calls.push(field_debug_call(None, quote!(#binding), field));
+                }
+            }
+            let pattern = quote!(#type_name::#variant_name ( #(#patterns),* ));
+            let builder = quote! {
+                f.debug_tuple(stringify!(#variant_name)) #(#calls)* .finish()
+            };
+            (pattern, builder)
+        }
+        Fields::Unit => {
+            let pattern = quote!(#type_name::#variant_name);
+            let builder = quote!(f.write_str(stringify!(#variant_name)));
+            (pattern, builder)
+        }
     }
 }